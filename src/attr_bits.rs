@@ -0,0 +1,154 @@
+//! A compact bitmask representation of an observed attribute-type set, used as the key for
+//! [crate::AttributeCounts]'s per-record map. Building this key used to mean allocating and
+//! sorting a `SmallVec` for every single record; representing the set as a fixed-width integer
+//! instead makes counting an allocation-free OR of bits, with the set canonical by construction.
+use bgpkit_parser::models::AttrType;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::sync::OnceLock;
+
+/// The attribute types we track individually. Anything not in this list still gets counted, but
+/// collapses into [OTHER_BIT] in the bitmask so a single unknown/rare attribute type can't blow up
+/// the number of distinct sets.
+pub(crate) const KNOWN_ATTR_TYPES: [AttrType; 31] = {
+    use AttrType::*;
+    [
+        ORIGIN,
+        AS_PATH,
+        NEXT_HOP,
+        MULTI_EXIT_DISCRIMINATOR,
+        LOCAL_PREFERENCE,
+        ATOMIC_AGGREGATE,
+        AGGREGATOR,
+        COMMUNITIES,
+        ORIGINATOR_ID,
+        CLUSTER_LIST,
+        CLUSTER_ID,
+        MP_REACHABLE_NLRI,
+        MP_UNREACHABLE_NLRI,
+        EXTENDED_COMMUNITIES,
+        AS4_PATH,
+        AS4_AGGREGATOR,
+        PMSI_TUNNEL,
+        TUNNEL_ENCAPSULATION,
+        TRAFFIC_ENGINEERING,
+        IPV6_ADDRESS_SPECIFIC_EXTENDED_COMMUNITIES,
+        AIGP,
+        PE_DISTINGUISHER_LABELS,
+        BGP_LS_ATTRIBUTE,
+        LARGE_COMMUNITIES,
+        BGPSEC_PATH,
+        ONLY_TO_CUSTOMER,
+        SFP_ATTRIBUTE,
+        BFD_DISCRIMINATOR,
+        BGP_PREFIX_SID,
+        ATTR_SET,
+        DEVELOPMENT,
+    ]
+};
+
+/// Reserved bit for any attribute type outside of [KNOWN_ATTR_TYPES].
+pub(crate) const OTHER_BIT: u32 = KNOWN_ATTR_TYPES.len() as u32;
+
+/// Sentinel key under which totals derived from [OTHER_BIT] are bucketed, since a scan of an
+/// [AttrBitSet] can't recover which specific unmapped attribute type(s) were actually observed.
+pub(crate) const OTHER_ATTR: AttrType = AttrType::Unknown(0);
+
+/// A set of observed [AttrType]s packed one-bit-per-type. `KNOWN_ATTR_TYPES.len() + 1` (for
+/// [OTHER_BIT]) comfortably fits in a `u64`; switch to `u128` if [KNOWN_ATTR_TYPES] ever grows
+/// past 64 entries.
+pub(crate) type AttrBitSet = u64;
+
+fn attr_index_table() -> &'static [u8; 256] {
+    static TABLE: OnceLock<[u8; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table = [OTHER_BIT as u8; 256];
+        for (index, attr) in KNOWN_ATTR_TYPES.into_iter().enumerate() {
+            table[u8::from(attr) as usize] = index as u8;
+        }
+        table
+    })
+}
+
+/// Dense bit index for `attr` within an [AttrBitSet]. Falls back to [OTHER_BIT] for any attribute
+/// type not in [KNOWN_ATTR_TYPES].
+pub(crate) fn bit_index(attr: AttrType) -> u32 {
+    attr_index_table()[u8::from(attr) as usize] as u32
+}
+
+/// Decodes an [AttrBitSet] back into the [KNOWN_ATTR_TYPES] it contains, by scanning each set bit
+/// through the reverse of [attr_index_table]. Does not report [OTHER_BIT]; see [describe_attr_bits]
+/// for a rendering that does.
+pub(crate) fn known_attrs_in(bits: AttrBitSet) -> impl Iterator<Item = AttrType> {
+    KNOWN_ATTR_TYPES
+        .into_iter()
+        .enumerate()
+        .filter(move |(index, _)| bits & (1 << index) != 0)
+        .map(|(_, attr)| attr)
+}
+
+/// Renders an [AttrBitSet] back into the names of the attribute types it contains. If [OTHER_BIT]
+/// is set, `+ OTHER` is appended to note that at least one unmapped attribute type was observed.
+pub(crate) fn describe_attr_bits(bits: AttrBitSet) -> String {
+    let known: Vec<AttrType> = known_attrs_in(bits).collect();
+
+    if bits & (1 << OTHER_BIT) != 0 {
+        format!("{:?} + OTHER", known)
+    } else {
+        format!("{:?}", known)
+    }
+}
+
+/// The bitmask key is already a well-distributed integer, so hashing it with a general-purpose
+/// algorithm like SipHash is wasted work; this hasher just returns the `u64` key unchanged.
+#[derive(Default)]
+pub(crate) struct IdentityHasher(u64);
+
+impl Hasher for IdentityHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, _bytes: &[u8]) {
+        unimplemented!("IdentityHasher only supports AttrBitSet (u64) keys")
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.0 = i;
+    }
+}
+
+pub(crate) type IdentityBuildHasher = BuildHasherDefault<IdentityHasher>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_index_round_trips_through_known_attrs_in() {
+        for attr in KNOWN_ATTR_TYPES {
+            let bits: AttrBitSet = 1 << bit_index(attr);
+            assert_eq!(known_attrs_in(bits).collect::<Vec<_>>(), vec![attr]);
+        }
+    }
+
+    #[test]
+    fn bit_index_falls_back_to_other_bit_for_unknown_attr_type() {
+        let unknown = AttrType::Unknown(253);
+        assert_eq!(bit_index(unknown), OTHER_BIT);
+        assert!(known_attrs_in(1 << OTHER_BIT).collect::<Vec<_>>().is_empty());
+    }
+
+    #[test]
+    fn describe_attr_bits_notes_other_when_other_bit_is_set() {
+        let bits: AttrBitSet = (1 << bit_index(AttrType::ORIGIN)) | (1 << OTHER_BIT);
+        let described = describe_attr_bits(bits);
+        assert!(described.contains("ORIGIN"));
+        assert!(described.contains("OTHER"));
+    }
+
+    #[test]
+    fn describe_attr_bits_omits_other_when_not_set() {
+        let bits: AttrBitSet = 1 << bit_index(AttrType::ORIGIN);
+        assert!(!describe_attr_bits(bits).contains("OTHER"));
+    }
+}
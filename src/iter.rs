@@ -1,12 +1,19 @@
 use bgpkit_parser::models::MrtRecord;
 use bgpkit_parser::{parse_mrt_record, ParserError};
+use std::fmt::{Display, Formatter};
 use std::io::ErrorKind::UnexpectedEof;
-use std::io::Read;
+use std::io::{Cursor, Read};
+
+/// Size of the MRT common header: a 4-byte timestamp, 2-byte type, 2-byte subtype, and the 4-byte
+/// length field we need to inspect before trusting it.
+const MRT_COMMON_HEADER_LEN: usize = 12;
 
 /// Alternative to [bgpkit_parser::BgpkitParser] which does not silently hide error messages
 pub struct MsgIter<R> {
     reader: R,
     is_finished: bool,
+    max_record_size: usize,
+    source_label: String,
 }
 
 impl<R> MsgIter<R> {
@@ -14,15 +21,34 @@ impl<R> MsgIter<R> {
         MsgIter {
             reader,
             is_finished: false,
+            max_record_size: usize::MAX,
+            source_label: String::new(),
         }
     }
+
+    /// Caps the declared length a single MRT record's header is allowed to claim. A truncated or
+    /// malicious file can otherwise request an enormous allocation for the record body; exceeding
+    /// this cap yields [MsgIterError::TooLarge] and ends the stream cleanly instead of letting the
+    /// parser attempt the allocation.
+    pub fn with_max_record_size(mut self, max_record_size: usize) -> Self {
+        self.max_record_size = max_record_size;
+        self
+    }
+
+    /// Identifies this reader's source (a URL or local path) in error messages, so a
+    /// [MsgIterError::TooLarge] is self-describing instead of relying on the caller to print it
+    /// alongside.
+    pub fn with_source_label(mut self, source_label: impl Into<String>) -> Self {
+        self.source_label = source_label.into();
+        self
+    }
 }
 
 impl<R> Iterator for MsgIter<R>
 where
     R: Read,
 {
-    type Item = Result<MrtRecord, ParserError>;
+    type Item = Result<MrtRecord, MsgIterError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         // Check if we returned a fatal error last iteration
@@ -30,8 +56,44 @@ where
             return None;
         }
 
+        let mut header = [0u8; MRT_COMMON_HEADER_LEN];
+        let mut header_len = 0;
+        while header_len < header.len() {
+            match self.reader.read(&mut header[header_len..]) {
+                Ok(0) => break,
+                Ok(n) => header_len += n,
+                Err(e) => {
+                    self.is_finished = true;
+                    return Some(Err(MsgIterError::Parser(ParserError::IoError(e))));
+                }
+            }
+        }
+
+        // Clean EOF right at a record boundary: nothing left to parse.
+        if header_len == 0 {
+            return None;
+        }
+
+        // The file ended partway through the common header itself.
+        if header_len < header.len() {
+            self.is_finished = true;
+            return Some(Err(MsgIterError::Parser(ParserError::IoError(
+                UnexpectedEof.into(),
+            ))));
+        }
+
+        let declared_length = u32::from_be_bytes(header[8..12].try_into().unwrap());
+        if declared_length as usize > self.max_record_size {
+            self.is_finished = true;
+            return Some(Err(MsgIterError::TooLarge {
+                declared_length,
+                max_record_size: self.max_record_size,
+                source: self.source_label.clone(),
+            }));
+        }
+
         let mut eof_checker = EofChecker {
-            reader: &mut self.reader,
+            reader: Cursor::new(header).chain(&mut self.reader),
             is_start: true,
             started_with_eof: false,
         };
@@ -41,9 +103,9 @@ where
             Err(_) if eof_checker.started_with_eof => None,
             Err(e) if is_probably_fatal_error(&e.error) => {
                 self.is_finished = true;
-                Some(Err(e.error))
+                Some(Err(MsgIterError::Parser(e.error)))
             }
-            Err(e) => Some(Err(e.error)),
+            Err(e) => Some(Err(MsgIterError::Parser(e.error))),
         }
     }
 }
@@ -63,6 +125,35 @@ fn is_probably_fatal_error(err: &ParserError) -> bool {
     }
 }
 
+/// Errors produced while iterating [MsgIter]. Wraps the underlying [ParserError], plus a distinct
+/// variant for a record whose declared length exceeds the configured cap.
+#[derive(Debug)]
+pub enum MsgIterError {
+    Parser(ParserError),
+    TooLarge {
+        declared_length: u32,
+        max_record_size: usize,
+        source: String,
+    },
+}
+
+impl Display for MsgIterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MsgIterError::Parser(e) => Display::fmt(e, f),
+            MsgIterError::TooLarge {
+                declared_length,
+                max_record_size,
+                source,
+            } => write!(
+                f,
+                "record from {:?} declared a length of {} bytes, which exceeds the {} byte cap",
+                source, declared_length, max_record_size
+            ),
+        }
+    }
+}
+
 /// Wraps around a reader and records if it hit the end of the file upon the very first read
 struct EofChecker<R: Read> {
     reader: R,
@@ -80,3 +171,60 @@ impl<R: Read> Read for EofChecker<R> {
         res
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_reader_yields_no_items() {
+        let mut iter = MsgIter::new(Cursor::new(Vec::<u8>::new()));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn truncated_header_yields_fatal_io_error() {
+        let mut iter = MsgIter::new(Cursor::new(vec![0u8; MRT_COMMON_HEADER_LEN - 1]));
+        match iter.next() {
+            Some(Err(MsgIterError::Parser(ParserError::IoError(e)))) => {
+                assert_eq!(e.kind(), UnexpectedEof)
+            }
+            other => panic!("expected a truncated-header IO error, got {:?}", other),
+        }
+        assert!(iter.next().is_none(), "iterator should end after a fatal error");
+    }
+
+    #[test]
+    fn oversized_declared_length_yields_too_large_with_source_label() {
+        let mut header = [0u8; MRT_COMMON_HEADER_LEN];
+        header[8..12].copy_from_slice(&100u32.to_be_bytes());
+
+        let mut iter = MsgIter::new(Cursor::new(header.to_vec()))
+            .with_max_record_size(10)
+            .with_source_label("test-source");
+
+        match iter.next() {
+            Some(Err(MsgIterError::TooLarge {
+                declared_length,
+                max_record_size,
+                source,
+            })) => {
+                assert_eq!(declared_length, 100);
+                assert_eq!(max_record_size, 10);
+                assert_eq!(source, "test-source");
+            }
+            other => panic!("expected TooLarge, got {:?}", other),
+        }
+        assert!(iter.next().is_none(), "iterator should end after a fatal error");
+    }
+
+    #[test]
+    fn too_large_display_includes_source_label() {
+        let err = MsgIterError::TooLarge {
+            declared_length: 100,
+            max_record_size: 10,
+            source: "test-source".to_string(),
+        };
+        assert!(err.to_string().contains("test-source"));
+    }
+}
@@ -0,0 +1,108 @@
+//! Reads MRT data from local files instead of fetching it from `BgpkitBroker`, so archived
+//! collections can be surveyed offline and benchmarks stay deterministic without network
+//! variance. Feeds the exact same [crate::iter::MsgIter] pipeline as the network path.
+use crate::prefetch::reader_for_buffer;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+const MRT_EXTENSIONS: [&str; 7] = [".gz", ".gzip", ".bz2", ".bz", ".lz4", ".lz", ".mrt"];
+
+pub struct LocalSource {
+    pub path: String,
+    pub reader: Box<dyn Read + Send>,
+}
+
+/// Resolves `path` into a list of MRT sources to read: a single file is used as-is, a directory is
+/// scanned recursively for files with a known MRT/compressed extension, and anything else is
+/// treated as a glob pattern.
+pub fn collect_sources(path: &str) -> io::Result<Vec<PathBuf>> {
+    let candidate = Path::new(path);
+    if candidate.is_dir() {
+        return Ok(scan_directory(candidate));
+    }
+    if candidate.is_file() {
+        return Ok(vec![candidate.to_path_buf()]);
+    }
+
+    let matches = glob::glob(path)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?
+        .flatten()
+        .collect();
+    Ok(matches)
+}
+
+fn scan_directory(dir: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                found.extend(scan_directory(&path));
+            } else if is_mrt_source(&path) {
+                found.push(path);
+            }
+        }
+    }
+    found
+}
+
+fn is_mrt_source(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    MRT_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+}
+
+fn is_compressed(path: &str) -> bool {
+    path.ends_with(".gz")
+        || path.ends_with(".gzip")
+        || path.ends_with(".bz2")
+        || path.ends_with(".bz")
+        || path.ends_with(".lz4")
+        || path.ends_with(".lz")
+}
+
+/// Opens `path` for reading. Uncompressed files are memory-mapped so large RIB dumps are parsed
+/// without copying into a heap buffer; compressed files are streamed through a `BufReader` and the
+/// same decompressor selection used for remote downloads.
+pub fn open_source(path: &Path) -> io::Result<LocalSource> {
+    let file = std::fs::File::open(path)?;
+    let path_str = path.to_string_lossy().into_owned();
+
+    let reader = if is_compressed(&path_str) {
+        reader_for_buffer(&path_str, BufReader::new(file))
+    } else {
+        // SAFETY: standard caveat of memory-mapped files -- we assume `path` is not concurrently
+        // truncated or mutated while this run is reading it.
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        Box::new(MmapGuard { mmap, index: 0 }) as Box<dyn Read + Send>
+    };
+
+    Ok(LocalSource {
+        path: path_str,
+        reader,
+    })
+}
+
+/// Exposes a memory-mapped file as a [Read] + [BufRead] source without copying its contents.
+struct MmapGuard {
+    mmap: memmap2::Mmap,
+    index: usize,
+}
+
+impl Read for MmapGuard {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read_len = buf.len().min(self.mmap.len() - self.index);
+        buf[..read_len].copy_from_slice(&self.mmap[self.index..self.index + read_len]);
+        self.index += read_len;
+        Ok(read_len)
+    }
+}
+
+impl BufRead for MmapGuard {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(&self.mmap[self.index..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.index += amt;
+    }
+}
@@ -1,7 +1,12 @@
+mod attr_bits;
 mod iter;
+mod local_input;
+mod output;
 mod prefetch;
 
+use crate::attr_bits::{bit_index, describe_attr_bits, AttrBitSet, IdentityBuildHasher};
 use crate::iter::MsgIter;
+use crate::output::{FileSink, KafkaSink, OutputSink};
 use crate::prefetch::PrefetchResult;
 use bgpkit_broker::BgpkitBroker;
 use bgpkit_parser::models::Bgp4Mp::*;
@@ -13,19 +18,21 @@ use bgpkit_parser::models::{
 };
 use chrono::{Days, Utc};
 use rayon::prelude::*;
-use smallvec::SmallVec;
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::path::PathBuf;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering::SeqCst;
+use std::sync::Arc;
 use std::time::Instant;
 
 const OUTPUT_FILE: &str = "output.txt";
 
 const MAX_PREFETCH_BUFFER_SIZE: usize = 1 << 30; // 1GB
-const PREFETCH_BUFFER_SPACE: usize = 32 << 30; // 32GB (my system has 64GB, but it only uses ~6GB)
+const BLOCK_RAM_BUFFER_MAX: usize = 32 << 30; // 32GB (my system has 64GB, but it only uses ~6GB)
+
+// A single MRT record declaring a length past this is treated as corrupt rather than allocated.
+const MAX_MRT_RECORD_SIZE: usize = 1 << 30; // 1GB
 
 // Counters to assist with printing progress
 static TOTAL_ITEMS: AtomicUsize = AtomicUsize::new(0);
@@ -33,6 +40,16 @@ static COMPLETED: AtomicUsize = AtomicUsize::new(0);
 
 fn main() {
     let start_time = Instant::now();
+    let sink = build_output_sink();
+
+    // A local path or glob as the first argument surveys archived files offline instead of
+    // fetching the last day's worth of data from the broker.
+    if let Some(local_path) = std::env::args().nth(1) {
+        run_local_survey(&local_path, sink.as_ref());
+        println!("Finished in {:?}", start_time.elapsed());
+        return;
+    }
+
     let yesterdays_broker_items = BgpkitBroker::new()
         .page_size(1000)
         .ts_start((Utc::now() - Days::new(1)).timestamp())
@@ -56,13 +73,11 @@ fn main() {
         .cloned()
         .collect::<Vec<_>>();
 
-    let mut output_file = BufWriter::new(File::create(OUTPUT_FILE).unwrap());
-
     TOTAL_ITEMS.store(updates.len(), SeqCst);
     let updates_start_time = Instant::now();
-    let update_counts = prefetch::prefetch_iter(updates, 32, 32)
+    let update_counts = prefetch::prefetch_iter(updates, 32, 32, BLOCK_RAM_BUFFER_MAX)
         .par_bridge()
-        .map(map_broker_item_to_counts)
+        .map(|item| map_broker_item_to_counts(item, sink.as_ref()))
         .reduce(AttributeCounts::default, AttributeCounts::reduce);
 
     println!("\nBGP update attribute counts:");
@@ -72,16 +87,14 @@ fn main() {
         updates_start_time.elapsed()
     );
 
-    writeln!(&mut output_file, "BGP update attribute counts:").unwrap();
-    writeln!(&mut output_file, "{}", update_counts).unwrap();
-    output_file.flush().unwrap();
+    sink.flush_totals("BGP update attribute counts", &update_counts);
 
     TOTAL_ITEMS.store(rib_dumps.len(), SeqCst);
     COMPLETED.store(0, SeqCst);
     let rib_dumps_start_time = Instant::now();
-    let rib_counts = prefetch::prefetch_iter(rib_dumps, 32, 32)
+    let rib_counts = prefetch::prefetch_iter(rib_dumps, 32, 32, BLOCK_RAM_BUFFER_MAX)
         .par_bridge()
-        .map(map_broker_item_to_counts)
+        .map(|item| map_broker_item_to_counts(item, sink.as_ref()))
         .reduce(AttributeCounts::default, AttributeCounts::reduce);
 
     println!("\nBGP rib dump attribute counts:");
@@ -91,19 +104,109 @@ fn main() {
         rib_dumps_start_time.elapsed()
     );
 
-    writeln!(&mut output_file, "\nBGP rib dump attribute counts:").unwrap();
-    writeln!(&mut output_file, "{}", rib_counts).unwrap();
+    sink.flush_totals("BGP rib dump attribute counts", &rib_counts);
 
     println!("Finished in {:?}", start_time.elapsed());
 }
 
-fn map_broker_item_to_counts(item: PrefetchResult) -> AttributeCounts {
+/// Picks the output sink for this run: a Kafka sink when `KAFKA_BROKERS`/`KAFKA_TOPIC` are set in
+/// the environment, falling back to the original file-based totals dump otherwise.
+fn build_output_sink() -> Arc<dyn OutputSink> {
+    if let (Ok(brokers), Ok(topic)) = (
+        std::env::var("KAFKA_BROKERS"),
+        std::env::var("KAFKA_TOPIC"),
+    ) {
+        let client_id =
+            std::env::var("KAFKA_CLIENT_ID").unwrap_or_else(|_| "bgp_attribute_survey".into());
+        match KafkaSink::new(&brokers, &topic, &client_id) {
+            Ok(sink) => return Arc::new(sink),
+            Err(err) => println!(
+                "Failed to initialize Kafka sink ({}), falling back to file output",
+                err
+            ),
+        }
+    }
+
+    Arc::new(FileSink::new(OUTPUT_FILE).expect("failed to create output file"))
+}
+
+/// Surveys local MRT sources resolved from `path` (a file, a glob, or a directory scanned
+/// recursively) instead of fetching from `BgpkitBroker`.
+fn run_local_survey(path: &str, sink: &dyn OutputSink) {
+    let sources = local_input::collect_sources(path)
+        .unwrap_or_else(|err| panic!("failed to resolve input path {:?}: {}", path, err));
+
+    if sources.is_empty() {
+        println!("No MRT sources found at {:?}", path);
+        return;
+    }
+
+    TOTAL_ITEMS.store(sources.len(), SeqCst);
+    let local_start_time = Instant::now();
+    let counts = sources
+        .into_par_iter()
+        .map(|path| map_local_source_to_counts(path, sink))
+        .reduce(AttributeCounts::default, AttributeCounts::reduce);
+
+    println!("\nLocal MRT attribute counts:");
+    println!("{}", counts);
+    println!(
+        "\nFinished reading local sources in {:?}\n",
+        local_start_time.elapsed()
+    );
+
+    sink.flush_totals("Local MRT attribute counts", &counts);
+}
+
+fn map_local_source_to_counts(path: PathBuf, sink: &dyn OutputSink) -> AttributeCounts {
+    let start_time = Instant::now();
+    let mut attribute_counts = AttributeCounts::default();
+
+    let source = match local_input::open_source(&path) {
+        Ok(v) => v,
+        Err(err) => {
+            println!("Failed to open {:?}: {}", path, err);
+            return attribute_counts;
+        }
+    };
+
+    for record in MsgIter::new(source.reader)
+        .with_max_record_size(MAX_MRT_RECORD_SIZE)
+        .with_source_label(&source.path)
+    {
+        match record {
+            Ok(x) => attribute_counts.count_record(
+                x.message,
+                &source.path,
+                x.common_header.timestamp,
+                sink,
+            ),
+            Err(err) => println!("Error in {}: {}", source.path, err),
+        }
+    }
+
+    println!(
+        "[{}/{}] Finished {} in {:?}",
+        COMPLETED.fetch_add(1, SeqCst) + 1,
+        TOTAL_ITEMS.load(SeqCst),
+        source.path,
+        start_time.elapsed()
+    );
+    attribute_counts
+}
+
+fn map_broker_item_to_counts(item: PrefetchResult, sink: &dyn OutputSink) -> AttributeCounts {
     let start_time = Instant::now();
     let mut attribute_counts = AttributeCounts::default();
 
-    for record in MsgIter::new(item.reader) {
+    for record in MsgIter::new(item.reader)
+        .with_max_record_size(MAX_MRT_RECORD_SIZE)
+        .with_source_label(&item.url)
+    {
         match record {
-            Ok(x) => attribute_counts.count_record(x.message),
+            Ok(x) => {
+                attribute_counts.count_record(x.message, &item.url, x.common_header.timestamp, sink)
+            }
             Err(err) => println!("Error in {}: {}", item.url, err),
         }
     }
@@ -118,92 +221,82 @@ fn map_broker_item_to_counts(item: PrefetchResult) -> AttributeCounts {
     attribute_counts
 }
 
-type AttrTypeList = SmallVec<[AttrType; 6]>;
-
 #[derive(Clone)]
-struct AttributeCounts {
-    map: HashMap<AttrTypeList, u64>,
+pub(crate) struct AttributeCounts {
+    map: HashMap<AttrBitSet, u64, IdentityBuildHasher>,
     totals: HashMap<AttrType, u64>,
 }
 
 impl Default for AttributeCounts {
     fn default() -> Self {
-        use AttrType::*;
-        let all_attributes = [
-            ORIGIN,
-            AS_PATH,
-            NEXT_HOP,
-            MULTI_EXIT_DISCRIMINATOR,
-            LOCAL_PREFERENCE,
-            ATOMIC_AGGREGATE,
-            AGGREGATOR,
-            COMMUNITIES,
-            ORIGINATOR_ID,
-            CLUSTER_LIST,
-            CLUSTER_ID,
-            MP_REACHABLE_NLRI,
-            MP_UNREACHABLE_NLRI,
-            EXTENDED_COMMUNITIES,
-            AS4_PATH,
-            AS4_AGGREGATOR,
-            PMSI_TUNNEL,
-            TUNNEL_ENCAPSULATION,
-            TRAFFIC_ENGINEERING,
-            IPV6_ADDRESS_SPECIFIC_EXTENDED_COMMUNITIES,
-            AIGP,
-            PE_DISTINGUISHER_LABELS,
-            BGP_LS_ATTRIBUTE,
-            LARGE_COMMUNITIES,
-            BGPSEC_PATH,
-            ONLY_TO_CUSTOMER,
-            SFP_ATTRIBUTE,
-            BFD_DISCRIMINATOR,
-            BGP_PREFIX_SID,
-            ATTR_SET,
-            DEVELOPMENT,
-        ];
-
         let mut totals = HashMap::new();
-        for attr in all_attributes {
+        for attr in attr_bits::KNOWN_ATTR_TYPES {
             totals.insert(attr, 0);
         }
 
         AttributeCounts {
-            map: HashMap::new(),
+            map: HashMap::default(),
             totals,
         }
     }
 }
 
 impl AttributeCounts {
-    fn count_record(&mut self, record: MrtMessage) {
+    fn count_record(
+        &mut self,
+        record: MrtMessage,
+        url: &str,
+        timestamp: u32,
+        sink: &dyn OutputSink,
+    ) {
         match record {
-            TableDumpMessage(TableDump { attributes, .. }) => self.add_to_count(attributes),
+            TableDumpMessage(TableDump { attributes, .. }) => {
+                self.add_to_count(attributes, url, timestamp, sink)
+            }
             TableDumpV2Message(PeerIndexTable(_)) => {}
             TableDumpV2Message(
                 RibAfi(RibAfiEntries { rib_entries, .. })
                 | RibGeneric(RibGenericEntries { rib_entries, .. }),
             ) => rib_entries
                 .into_iter()
-                .for_each(|entry| self.add_to_count(entry.attributes)),
+                .for_each(|entry| self.add_to_count(entry.attributes, url, timestamp, sink)),
             Bgp4Mp(StateChange(_)) => {}
             Bgp4Mp(Message(Bgp4MpMessage { bgp_message, .. })) => match bgp_message {
-                BgpMessage::Update(update) => self.add_to_count(update.attributes),
+                BgpMessage::Update(update) => {
+                    self.add_to_count(update.attributes, url, timestamp, sink)
+                }
                 BgpMessage::Open(_) | BgpMessage::Notification(_) | BgpMessage::KeepAlive => {}
             },
         }
     }
 
-    fn add_to_count(&mut self, attributes: Attributes) {
-        let mut observed_types =
-            SmallVec::from_iter((&*attributes).into_iter().map(|x| x.attr_type));
-        observed_types.sort_unstable_by_key(|x| u8::from(*x));
+    fn add_to_count(
+        &mut self,
+        attributes: Attributes,
+        url: &str,
+        timestamp: u32,
+        sink: &dyn OutputSink,
+    ) {
+        let mut bits: AttrBitSet = 0;
+        for attr in &*attributes {
+            bits |= 1 << bit_index(attr.attr_type);
+        }
 
-        for x in &observed_types {
-            *self.totals.entry(*x).or_default() += 1;
+        sink.record(url, timestamp, bits);
+
+        // `totals` is populated from the same bit scan as `map`'s key, per the request, rather
+        // than by walking the raw attribute list. A record with the same attribute type repeated
+        // more than once now only contributes 1 to that type's total, not its occurrence count.
+        // Unmapped attribute types can't be told apart once collapsed into OTHER_BIT, so they're
+        // all bucketed under attr_bits::OTHER_ATTR instead of being dropped from totals entirely.
+        for attr in attr_bits::known_attrs_in(bits) {
+            *self.totals.entry(attr).or_default() += 1;
+        }
+        if bits & (1 << attr_bits::OTHER_BIT) != 0 {
+            *self.totals.entry(attr_bits::OTHER_ATTR).or_default() += 1;
         }
 
-        let count = self.map.entry(observed_types).or_default();
+        let count = self.map.entry(bits).or_default();
         *count += 1;
     }
 
@@ -229,9 +322,15 @@ impl Display for AttributeCounts {
         writeln!(f, "Attribute Group Counts")?;
         writeln!(f, "COUNT         PERCENT NAME")?;
         let total_items: u64 = self.map.values().copied().sum();
-        for (attrs, count) in items {
+        for (bits, count) in items {
             let percent = 100.0 * (*count as f64) / (total_items as f64);
-            writeln!(f, "{: <10}{: >10.05}% {:?}", count, percent, attrs)?;
+            writeln!(
+                f,
+                "{: <10}{: >10.05}% {}",
+                count,
+                percent,
+                describe_attr_bits(*bits)
+            )?;
         }
 
         writeln!(f, "\nTotal Attribute Counts")?;
@@ -240,7 +339,11 @@ impl Display for AttributeCounts {
         items.sort_unstable_by_key(|(_, x)| *x);
         for (attr, count) in items {
             let percent = 100.0 * (*count as f64) / (total_items as f64);
-            writeln!(f, "{: <10}{: >10.05}% {:?}", count, percent, attr)?;
+            if *attr == attr_bits::OTHER_ATTR {
+                writeln!(f, "{: <10}{: >10.05}% OTHER", count, percent)?;
+            } else {
+                writeln!(f, "{: <10}{: >10.05}% {:?}", count, percent, attr)?;
+            }
         }
 
         Ok(())
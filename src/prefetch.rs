@@ -7,24 +7,28 @@
 //! Technically, async/await would have been a more appropriate solution, but I don't really like
 //! having to work with async/await if I can avoid it. Plus [bgpkit_parser] does not support async
 //! and this was quite straightforward to write.
-use crate::{MAX_PREFETCH_BUFFER_SIZE, PREFETCH_BUFFER_SPACE};
+use crate::MAX_PREFETCH_BUFFER_SIZE;
 use bgpkit_broker::BrokerItem;
 use crossbeam_channel::{Receiver, Sender};
 use std::io;
 use std::io::ErrorKind::Other;
 use std::io::{BufRead, Read};
-use std::sync::atomic::AtomicIsize;
-use std::sync::atomic::Ordering::SeqCst;
+use std::sync::{Arc, Condvar, Mutex};
 
 pub struct PrefetchResult {
     pub url: String,
     pub reader: Box<dyn Read + Send>,
 }
 
+/// Run `threads` worker threads which fetch `sources` ahead of time and stream the results out of
+/// the returned iterator. `block_ram_buffer_max` bounds the total number of bytes the workers may
+/// hold resident in memory at once; workers block until enough of that budget is free before
+/// starting a new download instead of silently falling back to unbounded streaming.
 pub fn prefetch_iter(
     sources: Vec<BrokerItem>,
     threads: usize,
     mut buffer_limit: usize,
+    block_ram_buffer_max: usize,
 ) -> impl Iterator<Item = PrefetchResult> {
     let (send_items, recv_items) = crossbeam_channel::unbounded();
     sources
@@ -35,38 +39,68 @@ pub fn prefetch_iter(
     buffer_limit = buffer_limit.saturating_sub(threads);
     let (send_result, recv_result) = crossbeam_channel::bounded(buffer_limit);
 
+    let semaphore = Arc::new(ByteSemaphore::new(block_ram_buffer_max));
     for _ in 0..threads {
         let recv = recv_items.clone();
         let send = send_result.clone();
-        std::thread::spawn(move || worker_thread(recv, send));
+        let semaphore = semaphore.clone();
+        std::thread::spawn(move || worker_thread(recv, send, semaphore));
     }
 
     recv_result.into_iter()
 }
 
-static ESTIMATED_SPACE: AtomicIsize = AtomicIsize::new(PREFETCH_BUFFER_SPACE as isize);
+/// A counting semaphore over a byte budget. Unlike the `AtomicIsize` it replaces, `acquire` blocks
+/// the caller until enough permits are available instead of failing over to unbounded behavior, so
+/// the total number of resident bytes across all in-flight buffers never exceeds the budget it was
+/// constructed with.
+struct ByteSemaphore {
+    available: Mutex<usize>,
+    released: Condvar,
+}
 
-fn attempt_to_claim_space(estimated_size: i64) -> Option<isize> {
-    if estimated_size <= 0 || estimated_size as usize > MAX_PREFETCH_BUFFER_SIZE {
-        return None;
+impl ByteSemaphore {
+    fn new(total: usize) -> Self {
+        ByteSemaphore {
+            available: Mutex::new(total),
+            released: Condvar::new(),
+        }
     }
 
-    let estimated_buffer_capacity = 2 * estimated_size as isize;
-    ESTIMATED_SPACE
-        .fetch_update(SeqCst, SeqCst, |x| {
-            (x >= estimated_buffer_capacity).then(|| x - estimated_buffer_capacity)
-        })
-        .ok()?;
+    /// Block until `amount` bytes of budget can be claimed.
+    fn acquire(&self, amount: usize) {
+        let mut available = self.available.lock().unwrap();
+        while *available < amount {
+            available = self.released.wait(available).unwrap();
+        }
+        *available -= amount;
+    }
 
-    Some(estimated_buffer_capacity)
+    /// Return `amount` bytes of budget, waking any workers blocked in [Self::acquire].
+    fn release(&self, amount: usize) {
+        *self.available.lock().unwrap() += amount;
+        self.released.notify_all();
+    }
+
+    /// Move a claim from `from` bytes to `to` bytes, blocking if `to` is larger than `from` and
+    /// the difference is not immediately available.
+    fn adjust_claim(&self, from: usize, to: usize) {
+        if to < from {
+            self.release(from - to);
+        } else if to > from {
+            self.acquire(to - from);
+        }
+    }
 }
 
-/// Wrapper around a readable buffer which updates [ESTIMATED_SPACE] when the buffer is dropped.
+/// Wrapper around a readable buffer which releases its claimed space on [ByteSemaphore] when
+/// dropped.
 struct BufferGuard {
     buffer: Vec<u8>,
     index: usize,
     // Should be the same as the buffer's capacity, but store just to be safe
     claimed_space: usize,
+    semaphore: Arc<ByteSemaphore>,
 }
 
 impl Read for BufferGuard {
@@ -92,47 +126,89 @@ impl Drop for BufferGuard {
     fn drop(&mut self) {
         let buffer = std::mem::take(&mut self.buffer);
         drop(buffer);
-        ESTIMATED_SPACE.fetch_add(self.claimed_space as isize, SeqCst);
+        self.semaphore.release(self.claimed_space);
     }
 }
 
-fn worker_thread(recv: Receiver<BrokerItem>, send: Sender<PrefetchResult>) {
-    while let Ok(item) = recv.recv() {
-        let reader = match attempt_to_claim_space(item.rough_size) {
-            Some(requested_size) => {
-                // Pessimistically underside the buffer initially in the hopes of not reaching the
-                // approved size limit.
-                let mut buffer = Vec::with_capacity(item.rough_size.min(128 << 20) as usize);
-
-                // Read the full message into a buffer
-                let response = ureq::get(&item.url)
-                    .call()
-                    .map_err(|x| io::Error::new(Other, x))
-                    .and_then(|x| std::io::copy(&mut x.into_reader(), &mut buffer));
-
-                if let Err(err) = response {
-                    println!("Failed to fetch {:?}: {}", item.url, err);
-                    continue;
-                }
+/// Wrapper around a streamed reader (used for items too large to buffer in full) which releases
+/// its capped claim on [ByteSemaphore] when dropped.
+struct StreamGuard {
+    reader: Box<dyn Read + Send>,
+    claimed_space: usize,
+    semaphore: Arc<ByteSemaphore>,
+}
 
-                // Adjust estimated space to account for the differance in size from our estimate
-                ESTIMATED_SPACE.fetch_add(requested_size - buffer.capacity() as isize, SeqCst);
-                let reader = BufferGuard {
-                    claimed_space: buffer.capacity(),
-                    buffer,
-                    index: 0,
-                };
+impl Read for StreamGuard {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.semaphore.release(self.claimed_space);
+    }
+}
 
-                reader_for_buffer(&item.url, reader)
+fn worker_thread(
+    recv: Receiver<BrokerItem>,
+    send: Sender<PrefetchResult>,
+    semaphore: Arc<ByteSemaphore>,
+) {
+    while let Ok(item) = recv.recv() {
+        let estimated_size = item.rough_size.max(0) as usize;
+        let fits_in_memory = item.rough_size > 0 && estimated_size <= MAX_PREFETCH_BUFFER_SIZE;
+
+        // Items that don't fit the in-memory buffer still claim a capped share of the budget so
+        // they count against total concurrency instead of streaming for free.
+        let claimed_space = if fits_in_memory {
+            2 * estimated_size
+        } else {
+            MAX_PREFETCH_BUFFER_SIZE
+        };
+        semaphore.acquire(claimed_space);
+
+        let reader: Box<dyn Read + Send> = if fits_in_memory {
+            // Pessimistically undersize the buffer initially in the hopes of not reaching the
+            // approved size limit.
+            let mut buffer = Vec::with_capacity(estimated_size.min(128 << 20));
+
+            // Read the full message into a buffer
+            let response = ureq::get(&item.url)
+                .call()
+                .map_err(|x| io::Error::new(Other, x))
+                .and_then(|x| std::io::copy(&mut x.into_reader(), &mut buffer));
+
+            if let Err(err) = response {
+                println!("Failed to fetch {:?}: {}", item.url, err);
+                semaphore.release(claimed_space);
+                continue;
             }
-            None => {
-                // Just defer the base case to oneio since it will only process the data as it arrives
-                match oneio::get_reader(&item.url) {
-                    Ok(v) => v,
-                    Err(err) => {
-                        println!("Failed to fetch {:?}: {}", item.url, err);
-                        continue;
-                    }
+
+            // Adjust the claim to match the buffer's actual capacity instead of our estimate.
+            let actual_capacity = buffer.capacity();
+            semaphore.adjust_claim(claimed_space, actual_capacity);
+            let reader = BufferGuard {
+                claimed_space: actual_capacity,
+                buffer,
+                index: 0,
+                semaphore: semaphore.clone(),
+            };
+
+            reader_for_buffer(&item.url, reader)
+        } else {
+            // Still stream through oneio, but keep the capped claim held for as long as the
+            // stream is read from so total resident buffers stay within budget.
+            match oneio::get_reader(&item.url) {
+                Ok(reader) => Box::new(StreamGuard {
+                    reader,
+                    claimed_space,
+                    semaphore: semaphore.clone(),
+                }),
+                Err(err) => {
+                    println!("Failed to fetch {:?}: {}", item.url, err);
+                    semaphore.release(claimed_space);
+                    continue;
                 }
             }
         };
@@ -145,7 +221,13 @@ fn worker_thread(recv: Receiver<BrokerItem>, send: Sender<PrefetchResult>) {
     }
 }
 
-fn reader_for_buffer(file: &str, buffer: BufferGuard) -> Box<dyn Read + Send> {
+/// Picks a decompressor for `file` based on its extension and wraps `buffer` with it. Shared by
+/// the network prefetch path and [crate::local_input], which feeds this the same memory-mapped or
+/// buffered readers it would otherwise have pulled off the wire.
+pub(crate) fn reader_for_buffer<R: BufRead + Send + 'static>(
+    file: &str,
+    buffer: R,
+) -> Box<dyn Read + Send> {
     if file.ends_with(".gz") || file.ends_with(".gzip") {
         return Box::new(flate2::bufread::GzDecoder::new(buffer));
     }